@@ -0,0 +1,18 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! A fast, OS-independent monotonic clock backed by a stable hardware
+//! cycle counter where one is available.
+
+#[cfg(all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")))]
+#[path = "tsc_now.rs"]
+mod tsc_now;
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+#[path = "tsc_now_aarch64.rs"]
+mod tsc_now;
+
+#[cfg(any(
+    all(target_os = "linux", any(target_arch = "x86", target_arch = "x86_64")),
+    all(target_os = "linux", target_arch = "aarch64"),
+))]
+pub(crate) use tsc_now::{current_cycle, get_tsc_level, is_tsc_available, nanos_per_cycle, TSCLevel};
@@ -1,6 +1,8 @@
 // Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
 
 //! This module will be compiled when it's either linux_x86 or linux_x86_64.
+//! See `tsc_now_aarch64` for the aarch64 counterpart, which reads the ARM
+//! generic timer instead of the x86 TSC.
 
 use libc::{cpu_set_t, sched_setaffinity, CPU_SET};
 use std::io::prelude::*;
@@ -240,6 +242,94 @@ fn try_read_tsc_freq_khz() -> Result<u64, TscReadError> {
         .map_err(|e| TscReadError::FailedToParse((e, s)))
 }
 
+/// Attempts to derive the invariant TSC frequency straight from CPUID,
+/// without measuring anything.
+///
+/// Leaf 0x15 reports the TSC/core-crystal-clock ratio in EAX:EBX plus the
+/// nominal crystal frequency (in Hz) in ECX. Some CPU generations report
+/// ECX as 0, in which case we fall back to leaf 0x16, whose EAX gives the
+/// processor base frequency directly in MHz.
+fn try_read_tsc_freq_cpuid() -> Option<u64> {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::{__cpuid_count, __get_cpuid_max};
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::{__cpuid_count, __get_cpuid_max};
+
+    // SAFETY: `cpuid` is available on every x86/x86_64 target this module compiles for.
+    let max_leaf = unsafe { __get_cpuid_max(0).0 };
+    if max_leaf < 0x15 {
+        return None;
+    }
+
+    // SAFETY: leaf 0x15 was just confirmed to be supported above.
+    let leaf15 = unsafe { __cpuid_count(0x15, 0) };
+    if leaf15.ebx == 0 {
+        return None;
+    }
+
+    if leaf15.ecx != 0 {
+        if leaf15.eax == 0 {
+            return None;
+        }
+        return Some(leaf15.ecx as u64 * leaf15.ebx as u64 / leaf15.eax as u64);
+    }
+
+    if max_leaf >= 0x16 {
+        // SAFETY: leaf 0x16 was just confirmed to be supported above.
+        let leaf16 = unsafe { __cpuid_count(0x16, 0) };
+        return Some(leaf16.eax as u64 * 1_000_000);
+    }
+
+    None
+}
+
+/// Attempts to read the (virtual) TSC frequency exposed by the hypervisor,
+/// for guests where self-measurement is too noisy to trust but the host
+/// still reports an exact, stable rate.
+///
+/// Detects a hypervisor via the CPUID.1 ECX hypervisor-present bit, reads
+/// the vendor string from the hypervisor leaf range starting at
+/// 0x40000000, and for KVM/VMware reads the (virtual) TSC frequency in kHz
+/// from EAX of leaf 0x40000010.
+fn try_read_tsc_freq_hypervisor() -> Option<u64> {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::{__cpuid, __cpuid_count};
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+    // SAFETY: cpuid leaf 1 is always available.
+    let leaf1 = unsafe { __cpuid(1) };
+    if leaf1.ecx & (1 << 31) == 0 {
+        // Not running under a hypervisor.
+        return None;
+    }
+
+    // SAFETY: the hypervisor-present bit above guarantees the hypervisor
+    // leaf range starting at 0x40000000 is implemented.
+    let vendor_leaf = unsafe { __cpuid(0x4000_0000) };
+    let mut vendor = [0u8; 12];
+    vendor[0..4].copy_from_slice(&vendor_leaf.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&vendor_leaf.ecx.to_le_bytes());
+    vendor[8..12].copy_from_slice(&vendor_leaf.edx.to_le_bytes());
+
+    match &vendor {
+        b"KVMKVMKVM\0\0\0" | b"VMwareVMware" => {}
+        _ => return None,
+    }
+
+    if vendor_leaf.eax < 0x4000_0010 {
+        return None;
+    }
+
+    // SAFETY: leaf 0x40000010 was just confirmed to be supported above.
+    let leaf = unsafe { __cpuid_count(0x4000_0010, 0) };
+    if leaf.eax == 0 {
+        return None;
+    }
+
+    Some(leaf.eax as u64 * 1000)
+}
+
 /// Returns (1) cycles per second and (2) cycles from anchor.
 /// The result of subtracting `cycles_from_anchor` from newly fetched TSC
 /// can be used to
@@ -249,6 +339,16 @@ fn cycles_per_sec(anchor: Instant) -> (u64, u64) {
     let (cps, last_monotonic, last_tsc) = if let Ok(tsc_freq_khz) = try_read_tsc_freq_khz() {
         let (last_monotonic, last_tsc) = monotonic_with_tsc();
         (tsc_freq_khz * 1000, last_monotonic, last_tsc)
+    } else if let Some(tsc_hz) = try_read_tsc_freq_hypervisor() {
+        // Tried ahead of `try_read_tsc_freq_cpuid()`: under a hypervisor,
+        // leaf 0x15/0x16 is frequently passed through unmodified from the
+        // host and so may not reflect the guest's actual (virtual) TSC
+        // rate, whereas the hypervisor leaf is authoritative for the guest.
+        let (last_monotonic, last_tsc) = monotonic_with_tsc();
+        (tsc_hz, last_monotonic, last_tsc)
+    } else if let Some(tsc_hz) = try_read_tsc_freq_cpuid() {
+        let (last_monotonic, last_tsc) = monotonic_with_tsc();
+        (tsc_hz, last_monotonic, last_tsc)
     } else {
         _calculate_cycles_per_sec()
     };
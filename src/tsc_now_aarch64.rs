@@ -0,0 +1,136 @@
+// Copyright 2021 TiKV Project Authors. Licensed under Apache-2.0.
+
+//! This module will be compiled when it's linux_aarch64.
+//!
+//! Unlike the x86 TSC, the ARM generic timer's frequency (`CNTFRQ_EL0`) is
+//! architecturally guaranteed to be fixed and identical across all cores,
+//! so there is no need for the per-CPU sync dance or affinity spreading
+//! that `tsc_now` relies on to stabilize the x86 TSC.
+
+use std::cell::UnsafeCell;
+use std::time::Instant;
+
+static TSC_STATE: TSCState = TSCState {
+    is_tsc_available: UnsafeCell::new(false),
+    tsc_level: UnsafeCell::new(TSCLevel::Unstable),
+    nanos_per_cycle: UnsafeCell::new(1.0),
+};
+
+struct TSCState {
+    is_tsc_available: UnsafeCell<bool>,
+    tsc_level: UnsafeCell<TSCLevel>,
+    nanos_per_cycle: UnsafeCell<f64>,
+}
+
+unsafe impl Sync for TSCState {}
+
+#[ctor::ctor]
+unsafe fn init() {
+    let tsc_level = TSCLevel::get();
+    let is_tsc_available = matches!(tsc_level, TSCLevel::Stable { .. });
+    if is_tsc_available {
+        *TSC_STATE.nanos_per_cycle.get() = 1_000_000_000.0 / tsc_level.cycles_per_second() as f64;
+    }
+    *TSC_STATE.is_tsc_available.get() = is_tsc_available;
+    *TSC_STATE.tsc_level.get() = tsc_level;
+    std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+#[inline]
+pub(crate) fn is_tsc_available() -> bool {
+    unsafe { *TSC_STATE.is_tsc_available.get() }
+}
+
+#[inline]
+pub(crate) fn get_tsc_level() -> TSCLevel {
+    unsafe { (*TSC_STATE.tsc_level.get()).clone() }
+}
+
+#[inline]
+pub(crate) fn nanos_per_cycle() -> f64 {
+    unsafe { *TSC_STATE.nanos_per_cycle.get() }
+}
+
+#[inline]
+pub(crate) fn current_cycle() -> u64 {
+    match unsafe { &*TSC_STATE.tsc_level.get() } {
+        TSCLevel::Stable {
+            cycles_from_anchor, ..
+        } => tsc().wrapping_sub(*cycles_from_anchor),
+        TSCLevel::Unstable => panic!("tsc is unstable"),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TSCLevel {
+    Stable {
+        cycles_per_second: u64,
+        cycles_from_anchor: u64,
+    },
+    Unstable,
+}
+
+impl TSCLevel {
+    fn get() -> TSCLevel {
+        let cntfrq = tsc_freq();
+        if cntfrq == 0 {
+            return TSCLevel::Unstable;
+        }
+
+        let anchor = Instant::now();
+        let (last_monotonic, last_tsc) = monotonic_with_tsc();
+        let nanos_from_anchor = (last_monotonic - anchor).as_nanos();
+        let cycles_flied = cntfrq as f64 * nanos_from_anchor as f64 / 1_000_000_000.0;
+        let cycles_from_anchor = last_tsc - cycles_flied.ceil() as u64;
+
+        TSCLevel::Stable {
+            cycles_per_second: cntfrq,
+            cycles_from_anchor,
+        }
+    }
+
+    #[inline]
+    fn cycles_per_second(&self) -> u64 {
+        match self {
+            TSCLevel::Stable {
+                cycles_per_second, ..
+            } => *cycles_per_second,
+            TSCLevel::Unstable => panic!("tsc is unstable"),
+        }
+    }
+}
+
+/// Try to get the generic timer count and monotonic time at the same
+/// time. Due to get interrupted in half way may happen, they aren't
+/// guaranteed to represent the same instant.
+fn monotonic_with_tsc() -> (Instant, u64) {
+    (Instant::now(), tsc())
+}
+
+#[inline]
+fn tsc() -> u64 {
+    let cnt: u64;
+    unsafe {
+        // `isb` orders the counter read against surrounding instructions,
+        // as recommended by the Arm Architecture Reference Manual.
+        core::arch::asm!(
+            "isb",
+            "mrs {cnt}, cntvct_el0",
+            cnt = out(reg) cnt,
+            options(nomem, nostack),
+        );
+    }
+    cnt
+}
+
+/// Reads the fixed frequency (in Hz) of the generic timer from
+/// `CNTFRQ_EL0`. This register is invariant and identical across cores,
+/// so, unlike the x86 TSC, it never needs to be measured or synced.
+#[inline]
+fn tsc_freq() -> u64 {
+    let freq: u64;
+    unsafe {
+        core::arch::asm!("mrs {freq}, cntfrq_el0", freq = out(reg) freq, options(nomem, nostack));
+    }
+    freq
+}